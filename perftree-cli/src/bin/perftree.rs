@@ -1,164 +1,453 @@
-use perftree::{Diff, State};
+use perftree::{all_equal, parse_suite, BisectResult, Diff, Divergence, State};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, Editor, Helper};
+use std::cell::RefCell;
 use std::env;
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
 use std::process::exit;
+use std::rc::Rc;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+const COMMANDS: &[&str] = &[
+    "fen",
+    "moves",
+    "depth",
+    "root",
+    "parent",
+    "unmove",
+    "child",
+    "move",
+    "diff",
+    "clear_cache",
+    "bisect",
+    "suite",
+    "chess960",
+    "nochess960",
+    "ascii",
+    "unicode",
+    "color",
+    "nocolor",
+    "aggregate",
+    "expand",
+    "exit",
+    "quit",
+];
+
 fn usage() -> ! {
-    eprintln!("Usage: perftree <script>");
+    eprintln!("Usage: perftree [--ascii] [--engine <cmd>]... <script>");
     exit(1);
 }
 
-struct Prompt<R> {
-    lines: std::io::Lines<R>,
+struct DisplayOptions {
+    ascii: bool,
+    color: bool,
+    aggregate: bool,
 }
 
-impl<R> Prompt<R>
-where
-    R: BufRead,
-{
-    fn new(buf_read: R) -> Self {
-        Self {
-            lines: buf_read.lines(),
+impl DisplayOptions {
+    fn from_env(ascii: bool) -> DisplayOptions {
+        DisplayOptions {
+            ascii,
+            color: env::var_os("NO_COLOR").is_none(),
+            aggregate: false,
         }
     }
+}
+
+/// Reads `State::cached_moves` rather than calling `diff`, so Tab can't launch the script or block on Stockfish.
+struct CommandCompleter {
+    state: Rc<RefCell<State>>,
+}
+
+impl Completer for CommandCompleter {
+    type Candidate = Pair;
 
-    fn prompt(&mut self, ps: &str) -> io::Result<Option<String>> {
-        if atty::is(atty::Stream::Stdin) {
-            if atty::is(atty::Stream::Stdout) {
-                print!("{}", ps);
-                io::stdout().flush()?;
-            } else if atty::is(atty::Stream::Stderr) {
-                eprint!("{}", ps);
-                io::stderr().flush()?;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before_cursor = &line[..pos];
+        let word_start = before_cursor.rfind(' ').map_or(0, |i| i + 1);
+        let word = &before_cursor[word_start..];
+
+        let candidates = if word_start == 0 {
+            COMMANDS
+                .iter()
+                .filter(|cmd| cmd.starts_with(word))
+                .map(|cmd| Pair {
+                    display: cmd.to_string(),
+                    replacement: cmd.to_string(),
+                })
+                .collect()
+        } else {
+            let command = before_cursor[..word_start].split_whitespace().next();
+            match command {
+                Some("child") | Some("move") => self
+                    .state
+                    .borrow()
+                    .cached_moves()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|move_| move_.starts_with(word))
+                    .map(|move_| Pair {
+                        display: move_.clone(),
+                        replacement: move_,
+                    })
+                    .collect(),
+                _ => Vec::new(),
             }
-        }
-        self.lines.next().transpose()
+        };
+
+        Ok((word_start, candidates))
     }
 }
 
+impl Helper for CommandCompleter {}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+fn history_path() -> Option<PathBuf> {
+    if let Some(state_home) = env::var_os("XDG_STATE_HOME").filter(|s| !s.is_empty()) {
+        return Some(PathBuf::from(state_home).join("perftree").join("history"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".perftree_history"))
+}
+
 fn main() -> io::Result<()> {
-    let input = io::stdin();
-    let mut prompt = Prompt::new(input.lock());
+    let mut ascii = false;
+    let mut script = None;
+    let mut extra_engines = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--ascii" {
+            ascii = true;
+        } else if arg == "--engine" {
+            extra_engines.push(args.next().unwrap_or_else(|| usage()));
+        } else if script.is_none() {
+            script = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    let mut display = DisplayOptions::from_env(ascii);
+
     let mut output = StandardStream::stdout(ColorChoice::Auto);
+    let state = Rc::new(RefCell::new(State::new(
+        script.unwrap_or_else(|| usage()),
+        &extra_engines,
+    )?));
 
-    let mut state = State::new(env::args().nth(1).unwrap_or_else(|| usage()))?;
+    // Piped, non-interactive input shouldn't touch the history file at all.
+    let interactive = atty::is(atty::Stream::Stdin);
+    let history_path = history_path();
 
-    while let Some(line) = prompt.prompt("> ")? {
-        let mut words = line.split_whitespace();
-        let cmd = match words.next() {
-            Some(word) => word,
-            None => continue,
-        };
+    let config = Config::builder().auto_add_history(true).build();
+    let mut rl: Editor<CommandCompleter, DefaultHistory> =
+        Editor::with_config(config).map_err(io::Error::other)?;
+    rl.set_helper(Some(CommandCompleter {
+        state: state.clone(),
+    }));
 
-        match cmd {
-            "fen" => {
-                let fen = words.collect::<Vec<_>>().join(" ");
-                if fen.is_empty() {
-                    println!("{}", state.fen());
-                } else {
-                    state.set_fen(fen);
+    if interactive {
+        if let Some(path) = &history_path {
+            let _ = rl.load_history(path);
+        }
+    }
+
+    loop {
+        match rl.readline("> ") {
+            Ok(line) => {
+                let mut state = state.borrow_mut();
+                if !run_command(&line, &mut state, &mut output, &mut display)? {
+                    break;
                 }
             }
-            "moves" => {
-                let moves = words.map(|s| s.to_string()).collect::<Vec<_>>();
-                if moves.is_empty() {
-                    println!("{}", state.moves().join(" "));
-                } else {
-                    state.set_moves(moves);
-                }
+            Err(ReadlineError::Eof) | Err(ReadlineError::Interrupted) => break,
+            Err(e) => {
+                eprintln!("readline error: {}", e);
+                break;
             }
-            "depth" => {
-                if let Some(depth) = words.next() {
-                    let depth = match depth.parse() {
-                        Ok(x) => x,
-                        Err(e) => {
-                            eprintln!("cannot parse given depth: {}", e);
-                            continue;
-                        }
-                    };
-                    state.set_depth(depth);
-                } else {
-                    println!("{}", state.depth());
-                }
+        }
+    }
+
+    if interactive {
+        if let Some(path) = &history_path {
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
             }
-            "root" => {
-                state.goto_root();
+            let _ = rl.append_history(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_command(
+    line: &str,
+    state: &mut State,
+    output: &mut StandardStream,
+    display: &mut DisplayOptions,
+) -> io::Result<bool> {
+    let mut words = line.split_whitespace();
+    let cmd = match words.next() {
+        Some(word) => word,
+        None => return Ok(true),
+    };
+
+    match cmd {
+        "fen" => {
+            let fen = words.collect::<Vec<_>>().join(" ");
+            if fen.is_empty() {
+                println!("{}", state.fen());
+            } else {
+                state.set_fen(fen);
             }
-            "parent" | "unmove" => {
-                state.goto_parent();
+        }
+        "moves" => {
+            let moves = words.map(|s| s.to_string()).collect::<Vec<_>>();
+            if moves.is_empty() {
+                println!("{}", state.moves().join(" "));
+            } else {
+                state.set_moves(moves);
             }
-            "child" | "move" => {
-                if let Some(move_) = words.next() {
-                    state.goto_child(move_);
-                } else {
-                    eprintln!("missing argument, expected a child move");
-                }
+        }
+        "depth" => {
+            if let Some(depth) = words.next() {
+                let depth = match depth.parse() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("cannot parse given depth: {}", e);
+                        return Ok(true);
+                    }
+                };
+                state.set_depth(depth);
+            } else {
+                println!("{}", state.depth());
             }
-            "diff" => match state.diff() {
-                Ok(diff) => write_colored(&diff, &mut output)?,
-                Err(e) => eprintln!("cannot compute diff: {}", e),
-            },
-            "exit" | "quit" => {
-                break;
+        }
+        "root" => {
+            state.goto_root();
+        }
+        "parent" | "unmove" => {
+            state.goto_parent();
+        }
+        "child" | "move" => {
+            if let Some(move_) = words.next() {
+                state.goto_child(move_);
+            } else {
+                eprintln!("missing argument, expected a child move");
             }
-            "chess960" => {
-                state.set_chess960(true);
+        }
+        "diff" => match state.diff() {
+            Ok(diff) => write_colored(&diff, output, display)?,
+            Err(e) => eprintln!("cannot compute diff: {}", e),
+        },
+        "clear_cache" => {
+            state.clear_cache();
+        }
+        "bisect" => {
+            let keep_position = matches!(words.next(), Some("keep"));
+            match state.bisect(keep_position) {
+                Ok(result) => print_bisect_result(&result),
+                Err(e) => eprintln!("cannot bisect: {}", e),
+            }
+        }
+        "suite" => {
+            if let Some(path) = words.next() {
+                let positions = std::fs::File::open(path)
+                    .map(BufReader::new)
+                    .and_then(parse_suite);
+                match positions {
+                    Ok(positions) => match state.run_suite(&positions) {
+                        Ok(report) => {
+                            println!("{} passed, {} failed", report.pass(), report.fail());
+                            if let Some((fen, depth, diff)) = report.first_failure() {
+                                println!("first failing position (depth {}): {}", depth, fen);
+                                write_colored(diff, output, display)?;
+                            }
+                        }
+                        Err(e) => eprintln!("cannot run suite: {}", e),
+                    },
+                    Err(e) => eprintln!("cannot load suite file {:?}: {}", path, e),
+                }
+            } else {
+                eprintln!("missing argument, expected a path to a suite file");
             }
-            "nochess960" => {
-                state.set_chess960(false);
+        }
+        "exit" | "quit" => {
+            return Ok(false);
+        }
+        "chess960" => {
+            state.set_chess960(true);
+        }
+        "nochess960" => {
+            state.set_chess960(false);
+        }
+        "ascii" => {
+            display.ascii = true;
+        }
+        "unicode" => {
+            display.ascii = false;
+        }
+        "color" => {
+            display.color = true;
+        }
+        "nocolor" => {
+            display.color = false;
+        }
+        "aggregate" => {
+            display.aggregate = true;
+        }
+        "expand" => {
+            display.aggregate = false;
+        }
+        other => {
+            eprintln!("unknown command {:?}", other);
+        }
+    }
+    Ok(true)
+}
+
+fn engine_label(index: usize) -> String {
+    match index {
+        0 => "your engine".to_string(),
+        1 => "reference".to_string(),
+        n => format!("engine {}", n),
+    }
+}
+
+fn print_bisect_result(result: &BisectResult) {
+    println!("moves: {}", result.moves.join(" "));
+    match &result.divergence {
+        None => println!("no divergence found"),
+        Some(Divergence::Mismatch { move_, counts }) => {
+            print!("diverging move: {}", move_);
+            for count in counts {
+                match count {
+                    Some(count) => print!("  {}", count),
+                    None => print!("  -"),
+                }
             }
-            other => {
-                eprintln!("unknown command {:?}", other);
+            println!();
+        }
+        Some(Divergence::MissingMove { move_, counts }) => {
+            println!("illegal move: {} is not generated by every engine", move_);
+            for (i, count) in counts.iter().enumerate() {
+                match count {
+                    Some(count) => println!("  {}: generated, count {}", engine_label(i), count),
+                    None => println!("  {}: not generated", engine_label(i)),
+                }
             }
         }
     }
-    Ok(())
 }
 
-pub fn write_colored<W>(diff: &Diff, mut write: W) -> io::Result<()>
+enum Row<'a> {
+    Move {
+        move_: &'a str,
+        counts: &'a [Option<u128>],
+    },
+    MatchSummary {
+        count: usize,
+    },
+}
+
+fn write_colored<W>(diff: &Diff, mut write: W, display: &DisplayOptions) -> io::Result<()>
 where
     W: WriteColor,
 {
+    let (branch, last_branch) = if display.ascii {
+        ("|-- ", "`-- ")
+    } else {
+        ("├── ", "└── ")
+    };
+
     let mut min_width = 0;
-    for &(lhs, rhs) in diff.child_count().values() {
-        if let Some(lhs) = lhs {
-            let digits = (lhs as f64).log10().ceil().max(0.0) as usize;
-            min_width = min_width.max(digits);
-        }
-        if let Some(rhs) = rhs {
-            let digits = (rhs as f64).log10().ceil().max(0.0) as usize;
+    for counts in diff.child_count().values() {
+        for &count in counts.iter().flatten() {
+            let digits = (count as f64).log10().ceil().max(0.0) as usize;
             min_width = min_width.max(digits);
         }
     }
 
-    for (move_, &(lhs, rhs)) in diff.child_count() {
-        if lhs != rhs {
-            write.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
-        }
-        write!(write, "{}", move_)?;
-        if let Some(lhs) = lhs {
-            write!(write, "  {:>width$}", lhs, width = min_width)?;
+    let mut rows = Vec::new();
+    let mut matching = 0;
+    for (move_, counts) in diff.child_count() {
+        if all_equal(counts.iter().copied()) {
+            matching += 1;
+            if !display.aggregate {
+                rows.push(Row::Move { move_, counts });
+            }
         } else {
-            write!(write, "  {:>width$}", "", width = min_width)?;
+            rows.push(Row::Move { move_, counts });
         }
-        if let Some(rhs) = rhs {
-            write!(write, "  {:>width$}", rhs, width = min_width)?;
-        } else {
-            write!(write, "  {:>width$}", "", width = min_width)?;
+    }
+    if display.aggregate && matching > 0 {
+        rows.push(Row::MatchSummary { count: matching });
+    }
+
+    let last_index = rows.len().saturating_sub(1);
+    for (i, row) in rows.iter().enumerate() {
+        let connector = if i == last_index { last_branch } else { branch };
+        match row {
+            Row::Move { move_, counts } => {
+                let mismatch = !all_equal(counts.iter().copied());
+                if mismatch && display.color {
+                    write.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+                }
+                write!(write, "{}{}", connector, move_)?;
+                for &count in *counts {
+                    match count {
+                        Some(count) => write!(write, "  {:>width$}", count, width = min_width)?,
+                        None => write!(write, "  {:>width$}", "", width = min_width)?,
+                    }
+                }
+                writeln!(write)?;
+                if mismatch && display.color {
+                    write.reset()?;
+                }
+            }
+            Row::MatchSummary { count } => {
+                writeln!(write, "{}{} moves match", connector, count)?;
+            }
         }
-        writeln!(write)?;
-        write.reset()?;
     }
 
     writeln!(write)?;
-    let (lhs, rhs) = diff.total_count();
-    if lhs != rhs {
+    let mismatch = !all_equal(diff.total_count().iter().copied().map(Some));
+    if mismatch && display.color {
         write.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
     }
-    write!(write, "total  {}  {}", lhs, rhs)?;
-    write.reset()?;
+    write!(write, "total")?;
+    for &count in diff.total_count() {
+        write!(write, "  {}", count)?;
+    }
+    if mismatch && display.color {
+        write.reset()?;
+    }
     writeln!(write)?;
 
+    let times: Vec<String> = diff
+        .time()
+        .iter()
+        .enumerate()
+        .map(|(i, time)| format!("{}: {:.2}s", engine_label(i), time.as_secs_f64()))
+        .collect();
+    writeln!(write, "{}", times.join("   "))?;
+
     Ok(())
 }
@@ -1,25 +1,34 @@
 use std::collections::BTreeMap;
 use std::io::{self, BufRead, BufReader, Write};
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
 
 const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
+type PerftKey = (String, Vec<String>, usize);
+
 pub struct State {
-    stockfish: Stockfish,
-    script: Script,
+    engines: Vec<Box<dyn Engine + Send>>,
+    cache: Vec<BTreeMap<PerftKey, Perft>>,
     fen: String,
     moves: Vec<String>,
     depth: usize,
 }
 
 impl State {
-    pub fn new<S>(cmd: S) -> io::Result<State>
+    pub fn new<S>(cmd: S, extra_engines: &[String]) -> io::Result<State>
     where
         S: Into<String>,
     {
+        let mut engines: Vec<Box<dyn Engine + Send>> =
+            vec![Box::new(Script::new(cmd)), Box::new(Stockfish::new()?)];
+        for cmd in extra_engines {
+            engines.push(Box::new(Script::new(cmd.clone())));
+        }
+        let cache = engines.iter().map(|_| BTreeMap::new()).collect();
         Ok(State {
-            stockfish: Stockfish::new()?,
-            script: Script::new(cmd),
+            engines,
+            cache,
             fen: INITIAL_FEN.to_string(),
             moves: Vec::new(),
             depth: 1,
@@ -34,6 +43,9 @@ impl State {
     where
         S: Into<String>,
     {
+        // Cache entries are keyed by the exact (fen, moves, depth) they were
+        // computed for, so they never go stale and don't need to be cleared
+        // here.
         self.fen = fen.into();
         self.moves.clear();
     }
@@ -72,56 +84,358 @@ impl State {
         self.moves.push(move_.into());
     }
 
+    pub fn set_chess960(&mut self, chess960: bool) {
+        for engine in &mut self.engines {
+            engine.set_chess960(chess960);
+        }
+        self.clear_cache();
+    }
+
+    pub fn clear_cache(&mut self) {
+        for cache in &mut self.cache {
+            cache.clear();
+        }
+    }
+
     pub fn diff(&mut self) -> io::Result<Diff> {
-        Ok(Diff::new(
-            &self
-                .script
-                .perft(&self.fen, &self.moves, self.depth - self.moves.len())?,
-            &self
-                .stockfish
-                .perft(&self.fen, &self.moves, self.depth - self.moves.len())?,
-        ))
+        let fen = &self.fen;
+        let moves = &self.moves;
+        let depth = self.depth - self.moves.len();
+        let key: PerftKey = (fen.clone(), moves.clone(), depth);
+
+        let mut perfts: Vec<Option<Perft>> = self
+            .cache
+            .iter()
+            .map(|cache| cache.get(&key).cloned())
+            .collect();
+        let mut time: Vec<Duration> = perfts.iter().map(|_| Duration::ZERO).collect();
+
+        // Only the engines that missed the cache need to actually run, and
+        // those that do run need not wait on each other.
+        let to_run: Vec<bool> = perfts.iter().map(Option::is_none).collect();
+        let results: Vec<Option<io::Result<(Perft, Duration)>>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .engines
+                .iter_mut()
+                .zip(&to_run)
+                .map(|(engine, &run)| {
+                    run.then(|| {
+                        scope.spawn(move || {
+                            let start = Instant::now();
+                            let perft = engine.perft(fen, moves, depth)?;
+                            Ok((perft, start.elapsed()))
+                        })
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.map(|handle| handle.join().expect("engine thread panicked")))
+                .collect()
+        });
+
+        for (i, result) in results.into_iter().enumerate() {
+            if let Some(result) = result {
+                let (perft, elapsed) = result?;
+                self.cache[i].insert(key.clone(), perft.clone());
+                perfts[i] = Some(perft);
+                time[i] = elapsed;
+            }
+        }
+
+        let perfts: Vec<Perft> = perfts
+            .into_iter()
+            .map(|perft| perft.expect("perft was either cached or just computed"))
+            .collect();
+        Ok(Diff::new(&perfts, time))
     }
 
-    pub fn set_chess960(&mut self, chess960: bool) {
-        self.stockfish.chess960 = chess960;
+    /// Same as `diff`, but for an arbitrary position, without disturbing the caller's fen/moves/depth.
+    pub fn diff_at(&mut self, fen: &str, moves: &[String], depth: usize) -> io::Result<Diff> {
+        let saved_fen = std::mem::replace(&mut self.fen, fen.to_string());
+        let saved_moves = std::mem::replace(&mut self.moves, moves.to_vec());
+        let saved_depth = std::mem::replace(&mut self.depth, depth);
+
+        let result = self.diff();
+
+        self.fen = saved_fen;
+        self.moves = saved_moves;
+        self.depth = saved_depth;
+        result
+    }
+
+    /// Legal child moves at the current position if cached; never runs an engine.
+    pub fn cached_moves(&self) -> Option<Vec<String>> {
+        let depth = self.depth.checked_sub(self.moves.len())?;
+        let key: PerftKey = (self.fen.clone(), self.moves.clone(), depth);
+        self.cache
+            .iter()
+            .find_map(|cache| cache.get(&key))
+            .map(|perft| perft.child_count().keys().cloned().collect())
+    }
+
+    pub fn run_suite(&mut self, positions: &[SuitePosition]) -> io::Result<SuiteReport> {
+        let mut queue: Vec<(usize, usize)> = Vec::new();
+        for (i, position) in positions.iter().enumerate() {
+            for &(depth, _) in &position.expected {
+                queue.push((i, depth));
+            }
+        }
+        // Sorted for cache locality; first_failure tracks original index `i`
+        // so the reorder doesn't change which position's failure gets
+        // reported. Within that position, the lowest failing depth is
+        // reported, whatever order its depths were listed in.
+        queue.sort_by(|&(i, depth_i), &(j, depth_j)| {
+            (&positions[i].fen, depth_i).cmp(&(&positions[j].fen, depth_j))
+        });
+
+        let mut report = SuiteReport {
+            pass: 0,
+            fail: 0,
+            first_failure: None,
+        };
+        for (i, depth) in queue {
+            let position = &positions[i];
+            let expected_count = position
+                .expected
+                .iter()
+                .find(|&&(d, _)| d == depth)
+                .expect("depth was taken from this position's own expected list")
+                .1;
+
+            let diff = self.diff_at(&position.fen, &[], depth)?;
+            if diff.total_count().iter().all(|&count| count == expected_count) {
+                report.pass += 1;
+            } else {
+                report.fail += 1;
+                let is_earliest = match &report.first_failure {
+                    Some((failed_i, ..)) => i < *failed_i,
+                    None => true,
+                };
+                if is_earliest {
+                    report.first_failure = Some((i, position.fen.clone(), depth, diff));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Descends following the first diverging move until it's a leaf, or the engines agree.
+    /// A move only some engines generated is an illegal/missing-move bug, not a count
+    /// mismatch, so it stops immediately rather than descending into a position that
+    /// doesn't exist for every engine.
+    pub fn bisect(&mut self, keep_position: bool) -> io::Result<BisectResult> {
+        let saved_moves = self.moves.clone();
+
+        let result = loop {
+            let diff = self.diff()?;
+            let remaining_depth = self.depth - self.moves.len();
+
+            match find_divergence(&diff) {
+                None => {
+                    break BisectResult {
+                        moves: self.moves.clone(),
+                        divergence: None,
+                    };
+                }
+                Some(divergence @ Divergence::MissingMove { .. }) => {
+                    break BisectResult {
+                        moves: self.moves.clone(),
+                        divergence: Some(divergence),
+                    };
+                }
+                Some(Divergence::Mismatch { move_, counts }) => {
+                    if remaining_depth <= 1 {
+                        break BisectResult {
+                            moves: self.moves.clone(),
+                            divergence: Some(Divergence::Mismatch { move_, counts }),
+                        };
+                    }
+                    self.goto_child(move_);
+                }
+            }
+        };
+
+        if !keep_position {
+            self.moves = saved_moves;
+        }
+        Ok(result)
+    }
+}
+
+fn find_divergence(diff: &Diff) -> Option<Divergence> {
+    let (move_, counts) = diff
+        .child_count()
+        .iter()
+        .find(|(_, counts)| !all_equal(counts.iter().copied()))?;
+    let (move_, counts) = (move_.clone(), counts.clone());
+    if counts.iter().any(Option::is_none) {
+        Some(Divergence::MissingMove { move_, counts })
+    } else {
+        Some(Divergence::Mismatch { move_, counts })
+    }
+}
+
+pub struct BisectResult {
+    pub moves: Vec<String>,
+    pub divergence: Option<Divergence>,
+}
+
+/// A single move at which the engines' perft results diverge.
+pub enum Divergence {
+    /// At least one engine didn't generate this move at all; an illegal or
+    /// missing-move bug, not a count mismatch.
+    MissingMove {
+        move_: String,
+        counts: Vec<Option<u128>>,
+    },
+    /// Every engine generated the move, but disagrees on its subtree count.
+    Mismatch {
+        move_: String,
+        counts: Vec<Option<u128>>,
+    },
+}
+
+pub struct SuitePosition {
+    fen: String,
+    expected: Vec<(usize, u128)>,
+}
+
+/// Parses `<fen> ;D1 20 ;D2 400 ;D3 8902`, one position per line.
+pub fn parse_suite<R>(reader: R) -> io::Result<Vec<SuitePosition>>
+where
+    R: BufRead,
+{
+    let mut positions = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(';');
+        let fen = fields
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing fen in suite line"))?
+            .trim()
+            .to_string();
+
+        let mut expected = Vec::new();
+        for field in fields {
+            let field = field.trim();
+            if field.is_empty() {
+                continue;
+            }
+            let mut words = field.split_whitespace();
+            let depth = words
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "missing depth in suite entry")
+                })?
+                .strip_prefix('D')
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "expected a depth marker like D1 in suite entry",
+                    )
+                })?
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let count = words
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "missing count in suite entry")
+                })?
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            expected.push((depth, count));
+        }
+
+        positions.push(SuitePosition { fen, expected });
+    }
+    Ok(positions)
+}
+
+pub struct SuiteReport {
+    pass: usize,
+    fail: usize,
+    first_failure: Option<(usize, String, usize, Diff)>,
+}
+
+impl SuiteReport {
+    pub fn pass(&self) -> usize {
+        self.pass
+    }
+
+    pub fn fail(&self) -> usize {
+        self.fail
+    }
+
+    pub fn first_failure(&self) -> Option<(&str, usize, &Diff)> {
+        self.first_failure
+            .as_ref()
+            .map(|(_, fen, depth, diff)| (fen.as_str(), *depth, diff))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Diff {
-    total_count: (u128, u128),
-    child_count: BTreeMap<String, (Option<u128>, Option<u128>)>,
+    total_count: Vec<u128>,
+    child_count: BTreeMap<String, Vec<Option<u128>>>,
+    time: Vec<Duration>,
 }
 
 impl Diff {
-    pub fn new(lhs: &Perft, rhs: &Perft) -> Diff {
-        let mut child_count = BTreeMap::new();
-        for (move_, &count) in &lhs.child_count {
-            child_count.entry(move_.clone()).or_insert((None, None)).0 = Some(count);
-        }
-        for (move_, &count) in &rhs.child_count {
-            child_count.entry(move_.clone()).or_insert((None, None)).1 = Some(count);
+    pub fn new(perfts: &[Perft], time: Vec<Duration>) -> Diff {
+        let mut child_count: BTreeMap<String, Vec<Option<u128>>> = BTreeMap::new();
+        for (i, perft) in perfts.iter().enumerate() {
+            for (move_, &count) in &perft.child_count {
+                let counts = child_count
+                    .entry(move_.clone())
+                    .or_insert_with(|| vec![None; perfts.len()]);
+                counts[i] = Some(count);
+            }
         }
         Diff {
-            total_count: (lhs.total_count, rhs.total_count),
+            total_count: perfts.iter().map(|perft| perft.total_count).collect(),
             child_count,
+            time,
         }
     }
 
-    pub fn total_count(&self) -> (u128, u128) {
-        self.total_count
+    pub fn total_count(&self) -> &[u128] {
+        &self.total_count
     }
 
-    pub fn child_count(&self) -> &BTreeMap<String, (Option<u128>, Option<u128>)> {
+    pub fn child_count(&self) -> &BTreeMap<String, Vec<Option<u128>>> {
         &self.child_count
     }
+
+    /// Time per engine (script, then reference); `Duration::ZERO` if served from cache.
+    pub fn time(&self) -> &[Duration] {
+        &self.time
+    }
+}
+
+pub fn all_equal<I>(values: I) -> bool
+where
+    I: IntoIterator<Item = Option<u128>>,
+{
+    let mut values = values.into_iter();
+    match values.next() {
+        Some(first) => values.all(|value| value == first),
+        None => true,
+    }
 }
 
-pub trait Engine {
+pub trait Engine: Send {
     fn perft(&mut self, fen: &str, moves: &[String], depth: usize) -> io::Result<Perft>;
+
+    fn set_chess960(&mut self, _chess960: bool) {}
 }
 
+#[derive(Debug, Clone)]
 pub struct Perft {
     total_count: u128,
     child_count: BTreeMap<String, u128>,
@@ -260,7 +574,6 @@ impl Stockfish {
 
 impl Engine for Stockfish {
     fn perft(&mut self, fen: &str, moves: &[String], depth: usize) -> io::Result<Perft> {
-        // Enable/disable Chess960
         write!(
             self.out,
             "setoption name UCI_Chess960 value {}",
@@ -319,6 +632,10 @@ impl Engine for Stockfish {
             total_count,
         })
     }
+
+    fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
 }
 
 impl Drop for Stockfish {
@@ -326,3 +643,94 @@ impl Drop for Stockfish {
         let _ = self.child.kill();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_suite_parses_multiple_depths() {
+        let input = b"startpos ;D1 20 ;D2 400\n# a comment\n\nr1bqkbnr/p ;D1 21\n".as_slice();
+        let positions = parse_suite(input).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].fen, "startpos");
+        assert_eq!(positions[0].expected, vec![(1, 20), (2, 400)]);
+        assert_eq!(positions[1].fen, "r1bqkbnr/p");
+        assert_eq!(positions[1].expected, vec![(1, 21)]);
+    }
+
+    #[test]
+    fn parse_suite_rejects_missing_depth_marker() {
+        let input = b"startpos ;1 20\n".as_slice();
+        assert!(parse_suite(input).is_err());
+    }
+
+    #[test]
+    fn all_equal_true_for_matching_values() {
+        assert!(all_equal([Some(5), Some(5), Some(5)]));
+    }
+
+    #[test]
+    fn all_equal_false_for_mismatch() {
+        assert!(!all_equal([Some(5), Some(5), Some(6)]));
+    }
+
+    #[test]
+    fn all_equal_true_for_empty() {
+        assert!(all_equal(std::iter::empty()));
+    }
+
+    #[test]
+    fn diff_new_merges_perfts_by_move() {
+        let lhs = Perft::new(20, BTreeMap::from([("a2a3".to_string(), 1), ("a2a4".to_string(), 1)]));
+        let rhs = Perft::new(21, BTreeMap::from([("a2a4".to_string(), 1), ("b2b3".to_string(), 1)]));
+        let diff = Diff::new(&[lhs, rhs], vec![Duration::ZERO, Duration::ZERO]);
+
+        assert_eq!(diff.total_count(), &[20, 21]);
+        assert_eq!(diff.child_count()["a2a3"], vec![Some(1), None]);
+        assert_eq!(diff.child_count()["a2a4"], vec![Some(1), Some(1)]);
+        assert_eq!(diff.child_count()["b2b3"], vec![None, Some(1)]);
+    }
+
+    #[test]
+    fn find_divergence_picks_the_mismatched_move() {
+        let lhs = Perft::new(2, BTreeMap::from([("a2a3".to_string(), 1), ("a2a4".to_string(), 1)]));
+        let rhs = Perft::new(2, BTreeMap::from([("a2a3".to_string(), 1), ("a2a4".to_string(), 2)]));
+        let diff = Diff::new(&[lhs, rhs], vec![Duration::ZERO, Duration::ZERO]);
+
+        match find_divergence(&diff).unwrap() {
+            Divergence::Mismatch { move_, counts } => {
+                assert_eq!(move_, "a2a4");
+                assert_eq!(counts, vec![Some(1), Some(2)]);
+            }
+            Divergence::MissingMove { .. } => panic!("expected a count mismatch"),
+        }
+    }
+
+    #[test]
+    fn find_divergence_flags_a_move_only_one_engine_generated() {
+        let lhs = Perft::new(
+            2,
+            BTreeMap::from([("a2a3".to_string(), 1), ("bogus_move".to_string(), 1)]),
+        );
+        let rhs = Perft::new(1, BTreeMap::from([("a2a3".to_string(), 1)]));
+        let diff = Diff::new(&[lhs, rhs], vec![Duration::ZERO, Duration::ZERO]);
+
+        match find_divergence(&diff).unwrap() {
+            Divergence::MissingMove { move_, counts } => {
+                assert_eq!(move_, "bogus_move");
+                assert_eq!(counts, vec![Some(1), None]);
+            }
+            Divergence::Mismatch { .. } => panic!("expected a missing move, not a count mismatch"),
+        }
+    }
+
+    #[test]
+    fn find_divergence_none_when_engines_agree() {
+        let lhs = Perft::new(1, BTreeMap::from([("a2a3".to_string(), 1)]));
+        let rhs = Perft::new(1, BTreeMap::from([("a2a3".to_string(), 1)]));
+        let diff = Diff::new(&[lhs, rhs], vec![Duration::ZERO, Duration::ZERO]);
+
+        assert!(find_divergence(&diff).is_none());
+    }
+}